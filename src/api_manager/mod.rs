@@ -0,0 +1,238 @@
+pub mod models;
+
+use crate::api_manager::models::{EventInfo, EventType, WebsocketEvents};
+use crate::journal;
+use crossbeam_channel::{Receiver, Sender};
+use futures_util::{SinkExt, StreamExt};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tracing::Instrument;
+use warp::ws::{Message, WebSocket};
+use warp::Filter;
+
+/// A connected client, and the frame encoding it negotiated at upgrade time.
+struct ClientHandle {
+    sender: mpsc::UnboundedSender<Message>,
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Json,
+    MsgPack,
+}
+
+impl Encoding {
+    /// Negotiated via the `?format=msgpack` query param; JSON remains the
+    /// default so existing clients are unaffected.
+    fn from_query(query: &HashMap<String, String>) -> Self {
+        match query.get("format").map(String::as_str) {
+            Some("msgpack") => Encoding::MsgPack,
+            _ => Encoding::Json,
+        }
+    }
+
+    fn encode(&self, event: &WebsocketEvents) -> Option<Message> {
+        match self {
+            Encoding::Json => serde_json::to_string(event).ok().map(Message::text),
+            // `WebsocketEvents` is internally tagged (`#[serde(tag = "type")]`),
+            // which relies on field names being present on the wire; plain
+            // `to_vec` serializes as a positional array and drops them, so the
+            // tag can't be read back on decode. `to_vec_named` keeps field names.
+            Encoding::MsgPack => rmp_serde::to_vec_named(event).ok().map(Message::binary),
+        }
+    }
+
+    fn decode(&self, message: &Message) -> Option<WebsocketEvents> {
+        match self {
+            Encoding::Json => serde_json::from_str(message.to_str().ok()?).ok(),
+            Encoding::MsgPack => rmp_serde::from_slice(message.as_bytes()).ok(),
+        }
+    }
+}
+
+type Clients = Arc<Mutex<Vec<ClientHandle>>>;
+
+pub struct ApiManager;
+
+impl ApiManager {
+    pub async fn start(
+        dist_sender: Sender<EventInfo>,
+        ws_receiver: Receiver<EventInfo>,
+        pool: SqlitePool,
+    ) {
+        let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+
+        let broadcast_clients = clients.clone();
+        tokio::task::spawn_blocking(move || {
+            for event in ws_receiver.iter() {
+                if let EventType::Websocket(ws_event) = event.event_type {
+                    let clients = broadcast_clients.clone();
+                    tokio::runtime::Handle::current().block_on(async move {
+                        let mut clients = clients.lock().await;
+                        clients.retain(|client| match client.encoding.encode(&ws_event) {
+                            Some(message) => client.sender.send(message).is_ok(),
+                            None => true,
+                        });
+                    });
+                }
+            }
+        });
+
+        let pool_for_tls = pool.clone();
+        let clients_filter = warp::any().map(move || clients.clone());
+        let dist_sender_filter = warp::any().map(move || dist_sender.clone());
+        let pool_filter = warp::any().map(move || pool.clone());
+
+        let ws_route = warp::path("ws")
+            .and(warp::ws())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(clients_filter)
+            .and(dist_sender_filter)
+            .and(pool_filter)
+            .map(
+                |ws: warp::ws::Ws,
+                 query: HashMap<String, String>,
+                 clients: Clients,
+                 dist_sender: Sender<EventInfo>,
+                 pool: SqlitePool| {
+                    let encoding = Encoding::from_query(&query);
+                    ws.on_upgrade(move |socket| {
+                        Self::handle_connection(socket, clients, dist_sender, pool, encoding)
+                    })
+                },
+            );
+
+        let metrics_route = warp::path("metrics").map(crate::metrics::gather);
+
+        let tls_acceptor = crate::tls::acceptor_from_settings(&pool_for_tls)
+            .await
+            .expect("TLS is configured but could not be loaded, refusing to start in cleartext");
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", 8080))
+            .await
+            .expect("Failed to bind websocket listener");
+        if tls_acceptor.is_some() {
+            tracing::info!("Serving websocket/HTTP traffic over TLS");
+        }
+
+        warp::serve(ws_route.or(metrics_route))
+            .serve_incoming(crate::tls::incoming(listener, tls_acceptor))
+            .await;
+    }
+
+    async fn handle_connection(
+        ws: WebSocket,
+        clients: Clients,
+        dist_sender: Sender<EventInfo>,
+        pool: SqlitePool,
+        encoding: Encoding,
+    ) {
+        let (mut ws_tx, mut ws_rx) = ws.split();
+        let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+
+        clients.lock().await.push(ClientHandle {
+            sender: tx.clone(),
+            encoding,
+        });
+
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                if ws_tx.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        while let Some(Ok(message)) = ws_rx.next().await {
+            if !message.is_text() && !message.is_binary() {
+                continue;
+            }
+
+            let event = match encoding.decode(&message) {
+                Some(event) => event,
+                None => continue,
+            };
+
+            let span = tracing::info_span!("websocket_event", kind = ?std::mem::discriminant(&event));
+
+            match event {
+                WebsocketEvents::ReplayRequest { since_id } => {
+                    Self::handle_replay_request(&pool, since_id, &tx, encoding)
+                        .instrument(span)
+                        .await;
+                }
+                event => {
+                    let _enter = span.enter();
+                    let _ = dist_sender.send(EventInfo {
+                        event_type: EventType::Websocket(event),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Answers a `ReplayRequest` directly on the requesting client's socket,
+    /// instead of broadcasting through `dist_sender`, so other connected
+    /// clients don't receive someone else's replay.
+    async fn handle_replay_request(
+        pool: &SqlitePool,
+        since_id: Option<i64>,
+        tx: &mpsc::UnboundedSender<Message>,
+        encoding: Encoding,
+    ) {
+        let events = match since_id {
+            Some(since_id) => journal::since(pool, since_id).await,
+            None => {
+                let limit = Self::client_terminal_amount(pool).await;
+                journal::last(pool, limit).await
+            }
+        };
+
+        let response = WebsocketEvents::ReplayResponse { events };
+        if let Some(message) = encoding.encode(&response) {
+            let _ = tx.send(message);
+        }
+    }
+
+    async fn client_terminal_amount(pool: &SqlitePool) -> i64 {
+        sqlx::query_scalar::<_, String>(
+            "SELECT value FROM settings WHERE id = 'N_clientTerminalAmount'",
+        )
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(500)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Encoding;
+    use crate::api_manager::models::WebsocketEvents;
+
+    #[test]
+    fn msgpack_round_trips_through_the_wire() {
+        let event = WebsocketEvents::TerminalRead {
+            message: "ok T:200/200".to_string(),
+        };
+
+        let message = Encoding::MsgPack.encode(&event).expect("can encode");
+        let decoded = Encoding::MsgPack.decode(&message).expect("can decode");
+
+        assert_eq!(decoded, event);
+    }
+
+    #[test]
+    fn json_round_trips_through_the_wire() {
+        let event = WebsocketEvents::PrintEnd;
+
+        let message = Encoding::Json.encode(&event).expect("can encode");
+        let decoded = Encoding::Json.decode(&message).expect("can decode");
+
+        assert_eq!(decoded, event);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::bridge::BridgeState;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct EventInfo {
+    pub event_type: EventType,
+}
+
+#[derive(Debug, Clone)]
+pub enum EventType {
+    Bridge(BridgeEvents),
+    Websocket(WebsocketEvents),
+    /// Tears down the active bridge connection.
+    KILL,
+    /// Top-level shutdown request (Ctrl-C/SIGTERM), handled by the `Manager`
+    /// loop itself rather than forwarded anywhere.
+    Shutdown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BridgeEvents {
+    ConnectionCreate { address: String, port: u32 },
+    ConnectionCreateError { error: String },
+    TerminalRead { message: String },
+    TerminalSend { message: String },
+    PrintStart { info: PrintInfo },
+    PrintEnd,
+    StateUpdate {
+        state: BridgeState,
+        description: StateDescription,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum WebsocketEvents {
+    TerminalRead { message: String },
+    TerminalSend { message: String },
+    StateUpdate {
+        state: BridgeState,
+        description: StateDescription,
+    },
+    PrintStart { info: PrintInfo },
+    PrintEnd,
+    /// Sent by a client that just connected (or reconnected) to ask for
+    /// everything it missed. `since_id` replays every event journaled after
+    /// that id; omit it to replay the last `N_clientTerminalAmount` events.
+    ReplayRequest { since_id: Option<i64> },
+    ReplayResponse {
+        events: Vec<crate::journal::JournaledEvent>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrintInfo {
+    pub file_name: String,
+    pub total_lines: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum StateDescription {
+    None,
+    Error { message: String },
+}
@@ -0,0 +1,127 @@
+use crate::api_manager::models::{BridgeEvents, EventInfo, EventType, StateDescription};
+use crossbeam_channel::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BridgeState {
+    CONNECTING,
+    CONNECTED,
+    PRINTING,
+    PAUSED,
+    DISCONNECTED,
+    ERRORED,
+}
+
+impl BridgeState {
+    /// Numeric representation used for the `bridge_state` Prometheus gauge.
+    pub fn as_metric_value(&self) -> i64 {
+        match self {
+            BridgeState::CONNECTING => 0,
+            BridgeState::CONNECTED => 1,
+            BridgeState::PRINTING => 2,
+            BridgeState::PAUSED => 3,
+            BridgeState::DISCONNECTED => 4,
+            BridgeState::ERRORED => 5,
+        }
+    }
+}
+
+pub struct Bridge {
+    dist_sender: Sender<EventInfo>,
+    bridge_receiver: Receiver<EventInfo>,
+    device_path: String,
+    baud_rate: u32,
+    shutdown: Option<oneshot::Receiver<()>>,
+}
+
+impl Bridge {
+    pub fn new(
+        dist_sender: Sender<EventInfo>,
+        bridge_receiver: Receiver<EventInfo>,
+        device_path: String,
+        baud_rate: u32,
+    ) -> Self {
+        Self {
+            dist_sender,
+            bridge_receiver,
+            device_path,
+            baud_rate,
+            shutdown: None,
+        }
+    }
+
+    /// Attaches a shutdown signal the owning `Manager` can fire to make
+    /// `start` return promptly instead of the thread being `abort()`-ed out
+    /// from under an open serial port.
+    pub fn with_shutdown(mut self, shutdown: oneshot::Receiver<()>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    pub fn start(&mut self) {
+        let port = match serialport::new(&self.device_path, self.baud_rate)
+            .timeout(Duration::from_millis(100))
+            .open()
+        {
+            Ok(port) => port,
+            Err(err) => {
+                let _ = self.dist_sender.send(EventInfo {
+                    event_type: EventType::Bridge(BridgeEvents::ConnectionCreateError {
+                        error: err.to_string(),
+                    }),
+                });
+                return;
+            }
+        };
+
+        let mut writer = port.try_clone().expect("Failed to clone serial port");
+        let mut reader = BufReader::new(port);
+
+        let _ = self.dist_sender.send(EventInfo {
+            event_type: EventType::Bridge(BridgeEvents::StateUpdate {
+                state: BridgeState::CONNECTED,
+                description: StateDescription::None,
+            }),
+        });
+
+        loop {
+            if self.should_shut_down() {
+                return;
+            }
+
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => return,
+                Ok(_) => {
+                    let _ = self.dist_sender.send(EventInfo {
+                        event_type: EventType::Bridge(BridgeEvents::TerminalRead {
+                            message: line.trim_end().to_string(),
+                        }),
+                    });
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::TimedOut => (),
+                Err(_) => return,
+            }
+
+            while let Ok(event) = self.bridge_receiver.try_recv() {
+                match event.event_type {
+                    EventType::Bridge(BridgeEvents::TerminalSend { message }) => {
+                        let _ = writeln!(writer, "{}", message);
+                    }
+                    EventType::KILL => return,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn should_shut_down(&mut self) -> bool {
+        match &mut self.shutdown {
+            Some(shutdown) => !matches!(shutdown.try_recv(), Err(oneshot::error::TryRecvError::Empty)),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,20 @@
+pub async fn check_updates() {
+    let current_version = env!("CARGO_PKG_VERSION");
+
+    match reqwest::get("https://api.github.com/repos/gcode-3d/server_v2/releases/latest").await {
+        Ok(response) => match response.json::<serde_json::Value>().await {
+            Ok(body) => {
+                if let Some(latest) = body.get("tag_name").and_then(|v| v.as_str()) {
+                    if latest.trim_start_matches('v') != current_version {
+                        println!(
+                            "[UPDATE] A newer version is available: {} (current: {})",
+                            latest, current_version
+                        );
+                    }
+                }
+            }
+            Err(err) => eprintln!("[UPDATE] Failed to parse update check response: {}", err),
+        },
+        Err(err) => eprintln!("[UPDATE] Failed to check for updates: {}", err),
+    }
+}
@@ -0,0 +1,107 @@
+use crate::api_manager::models::{EventInfo, EventType};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// A single row of the append-only `events` journal, as handed back to
+/// clients replaying history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournaledEvent {
+    pub id: i64,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub created_at: String,
+}
+
+/// Runs for the lifetime of the process, persisting every `EventType::Bridge`
+/// event that passes through the dist loop into the `events` table so it can
+/// be replayed by clients that connect mid-print.
+///
+/// Takes a tokio `mpsc` receiver rather than the `crossbeam_channel` used
+/// elsewhere so waiting for the next event is a proper `.await` instead of a
+/// blocking `recv()` that would park a Tokio worker thread for the life of
+/// the process; `UnboundedSender::send` on the other end stays a plain
+/// synchronous call, so callers in the non-async dist loop are unaffected.
+pub async fn run(pool: SqlitePool, mut receiver: UnboundedReceiver<EventInfo>) {
+    loop {
+        let event = match receiver.recv().await {
+            Some(event) => event,
+            None => return,
+        };
+
+        let EventType::Bridge(bridge_event) = event.event_type else {
+            continue;
+        };
+
+        let kind = bridge_event_kind(&bridge_event);
+        let payload = match serde_json::to_string(&bridge_event) {
+            Ok(payload) => payload,
+            Err(err) => {
+                eprintln!("[JOURNAL] Failed to serialize event: {}", err);
+                continue;
+            }
+        };
+
+        if let Err(err) = sqlx::query("INSERT INTO events (kind, payload, created_at) VALUES (?, ?, DATETIME('now'))")
+            .bind(kind)
+            .bind(payload)
+            .execute(&pool)
+            .await
+        {
+            eprintln!("[JOURNAL] Failed to persist event: {}", err);
+        }
+    }
+}
+
+fn bridge_event_kind(event: &crate::api_manager::models::BridgeEvents) -> &'static str {
+    use crate::api_manager::models::BridgeEvents;
+    match event {
+        BridgeEvents::ConnectionCreate { .. } => "ConnectionCreate",
+        BridgeEvents::ConnectionCreateError { .. } => "ConnectionCreateError",
+        BridgeEvents::TerminalRead { .. } => "TerminalRead",
+        BridgeEvents::TerminalSend { .. } => "TerminalSend",
+        BridgeEvents::PrintStart { .. } => "PrintStart",
+        BridgeEvents::PrintEnd => "PrintEnd",
+        BridgeEvents::StateUpdate { .. } => "StateUpdate",
+    }
+}
+
+/// Replays the last `limit` journaled events, oldest first.
+pub async fn last(pool: &SqlitePool, limit: i64) -> Vec<JournaledEvent> {
+    let rows = sqlx::query(
+        "SELECT id, kind, payload, created_at FROM events ORDER BY id DESC LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    let mut events = rows_to_events(rows);
+    events.reverse();
+    events
+}
+
+/// Replays every event journaled after `since_id`, oldest first.
+pub async fn since(pool: &SqlitePool, since_id: i64) -> Vec<JournaledEvent> {
+    let rows = sqlx::query("SELECT id, kind, payload, created_at FROM events WHERE id > ? ORDER BY id ASC")
+        .bind(since_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+
+    rows_to_events(rows)
+}
+
+fn rows_to_events(rows: Vec<sqlx::sqlite::SqliteRow>) -> Vec<JournaledEvent> {
+    rows.into_iter()
+        .map(|row| {
+            let payload: String = row.get("payload");
+            JournaledEvent {
+                id: row.get("id"),
+                kind: row.get("kind"),
+                payload: serde_json::from_str(&payload).unwrap_or(serde_json::Value::Null),
+                created_at: row.get("created_at"),
+            }
+        })
+        .collect()
+}
@@ -6,16 +6,22 @@ use api_manager::ApiManager;
 
 use bridge::Bridge;
 use crossbeam_channel::unbounded;
-use sqlx::{Connection, Executor, SqliteConnection};
+use sqlx::{sqlite::SqlitePoolOptions, Connection, Executor, SqliteConnection, SqlitePool};
+use std::time::{Duration, Instant};
 use tokio::{
     fs::OpenOptions,
+    signal::unix::{signal, SignalKind},
     spawn,
+    sync::{mpsc, oneshot},
     task::{spawn_blocking, JoinHandle},
 };
 mod api_manager;
 mod bridge;
 mod client_update_check;
-mod parser;
+mod journal;
+mod metrics;
+mod rate_limiter;
+mod tls;
 
 #[tokio::main]
 async fn main() {
@@ -26,64 +32,188 @@ async fn main() {
             ..Default::default()
         },
     ));
-    setup_db().await;
+    metrics::register();
+    let pool = setup_db().await;
+    init_tracing(&pool).await;
 
     client_update_check::check_updates().await;
 
     let mut manager = Manager::new();
-    manager.start().await;
+    manager.start(pool).await;
+}
+
+/// Upper bound on consecutive unattended bridge restarts before the `Manager`
+/// gives up and waits for an operator to intervene, so a device that never
+/// comes back (unplugged, broken firmware) doesn't hot-loop reconnects.
+const MAX_RESTART_ATTEMPTS: u32 = 5;
+
+/// Delay before the `attempt`-th automatic restart, doubling each time and
+/// capped at 64s so a flaky-but-recoverable device isn't punished forever.
+fn restart_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2u64.saturating_pow(attempt.min(6)))
 }
 
 struct Manager {
     bridge_thread: Option<JoinHandle<()>>,
+    bridge_shutdown: Option<oneshot::Sender<()>>,
     api_thread: Option<JoinHandle<()>>,
+    print_start: Option<Instant>,
+    print_span: Option<tracing::Span>,
+    /// Last address/baud a bridge was successfully asked to connect to, kept
+    /// around so a recoverable disconnect can be re-established automatically.
+    last_connection: Option<(String, u32)>,
+    /// Consecutive automatic restarts attempted since the last successful
+    /// connection, used to back off and eventually stop retrying.
+    restart_attempts: u32,
 }
 
 impl Manager {
     fn new() -> Self {
         Self {
             bridge_thread: None,
+            bridge_shutdown: None,
             api_thread: None,
+            print_start: None,
+            print_span: None,
+            last_connection: None,
+            restart_attempts: 0,
+        }
+    }
+
+    /// Tears down the currently running bridge thread, if any: fires its
+    /// shutdown signal, then aborts its task handle without waiting for it.
+    fn stop_bridge(&mut self) {
+        if let Some(shutdown) = self.bridge_shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(handle) = self.bridge_thread.take() {
+            handle.abort();
+        }
+    }
+
+    fn spawn_bridge(
+        &mut self,
+        dist_sender: &crossbeam_channel::Sender<EventInfo>,
+        bridge_receiver: &crossbeam_channel::Receiver<EventInfo>,
+        address: String,
+        port: u32,
+    ) {
+        self.stop_bridge();
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let dist_sender_clone = dist_sender.clone();
+        let bridge_receiver_clone = bridge_receiver.clone();
+        self.last_connection = Some((address.clone(), port));
+        self.bridge_shutdown = Some(shutdown_tx);
+        let span = tracing::info_span!("bridge_connection", %address, %port);
+        self.bridge_thread = Some(spawn_blocking(move || {
+            let _enter = span.enter();
+            let mut bridge =
+                Bridge::new(dist_sender_clone, bridge_receiver_clone, address, port)
+                    .with_shutdown(shutdown_rx);
+            bridge.start();
+        }));
+    }
+
+    /// Re-establishes the bridge against `last_connection` after a bounded,
+    /// backed-off delay, unless the attempt cap has been hit or the device is
+    /// no longer configured to start on boot. Shared by both paths that handle
+    /// a lost connection (a `DISCONNECTED`/`ERRORED` `StateUpdate` and
+    /// `ConnectionCreateError`). The bridge itself sends only a `CONNECTED`
+    /// `StateUpdate`; when its serial read loop ends it returns without
+    /// sending any event, so that case is not restarted.
+    async fn attempt_restart(
+        &mut self,
+        dist_sender: &crossbeam_channel::Sender<EventInfo>,
+        bridge_receiver: &crossbeam_channel::Receiver<EventInfo>,
+        restart_pool: &SqlitePool,
+    ) {
+        let Some((address, port)) = self.last_connection.clone() else {
+            return;
+        };
+
+        if self.restart_attempts >= MAX_RESTART_ATTEMPTS {
+            tracing::error!(
+                attempts = self.restart_attempts,
+                "Giving up on automatic bridge restart after repeated failures"
+            );
+            return;
+        }
+
+        if !start_on_boot(restart_pool).await {
+            return;
         }
+
+        let delay = restart_backoff(self.restart_attempts);
+        self.restart_attempts += 1;
+        tracing::info!(
+            attempt = self.restart_attempts,
+            ?delay,
+            "Re-establishing bridge connection after backoff"
+        );
+        tokio::time::sleep(delay).await;
+        self.spawn_bridge(dist_sender, bridge_receiver, address, port);
     }
 
-    async fn start<'a>(&'a mut self) {
+    async fn start<'a>(&'a mut self, pool: SqlitePool) {
         let (dist_sender, dist_receiver) = unbounded();
 
         let dist_sender_clone = dist_sender.clone();
         let (ws_sender, ws_receiver) = unbounded();
         let (bridge_sender, bridge_receiver) = unbounded();
-        self.api_thread = Some(spawn_blocking(move || {
-            let _ = spawn(ApiManager::start(dist_sender_clone, ws_receiver));
-        }));
+        let api_pool = pool.clone();
+        self.api_thread = Some(spawn(ApiManager::start(
+            dist_sender_clone,
+            ws_receiver,
+            api_pool,
+        )));
+
+        let (journal_sender, journal_receiver) = mpsc::unbounded_channel();
+        let command_rate_limiter = rate_limiter::CommandRateLimiter::from_settings(&pool).await;
+        let restart_pool = pool.clone();
+
+        let shutdown_dist_sender = dist_sender.clone();
+        spawn(async move {
+            wait_for_shutdown_signal().await;
+            let _ = shutdown_dist_sender.send(EventInfo {
+                event_type: EventType::Shutdown,
+            });
+        });
+
+        if let Some((address, port)) = start_on_boot_connection(&pool).await {
+            let _ = dist_sender.send(EventInfo {
+                event_type: EventType::Bridge(
+                    api_manager::models::BridgeEvents::ConnectionCreate { address, port },
+                ),
+            });
+        }
+
+        let journal_handle = spawn(journal::run(pool, journal_receiver));
 
         for event in dist_receiver.iter() {
+            if let EventType::Bridge(ref bridge_event) = event.event_type {
+                let _ = journal_sender.send(EventInfo {
+                    event_type: EventType::Bridge(bridge_event.clone()),
+                });
+            }
+
             match event.event_type {
                 EventType::Bridge(api_manager::models::BridgeEvents::ConnectionCreate {
                     address,
                     port,
                 }) => {
-                    println!(
-                        "[MAIN] Creating new bridge instance: {}:{}",
-                        &address, &port
-                    );
+                    tracing::info!(%address, %port, "Creating new bridge instance");
                     if self.bridge_thread.is_some() {
-                        panic!("Created connection before old connection was terminated");
-                        // continue;
+                        tracing::warn!("Replacing still-running bridge with a new connection");
                     }
 
-                    let dist_sender_clone = dist_sender.clone();
-                    let bridge_receiver_clone = bridge_receiver.clone();
-                    self.bridge_thread = Some(spawn_blocking(move || {
-                        let mut bridge =
-                            Bridge::new(dist_sender_clone, bridge_receiver_clone, address, port);
-                        bridge.start();
-                    }));
+                    self.spawn_bridge(&dist_sender, &bridge_receiver, address, port);
                 }
                 EventType::Bridge(api_manager::models::BridgeEvents::ConnectionCreateError {
                     error,
                 }) => {
-                    eprintln!("[BRIDGE] Creating connection caused an error: {} ", error);
+                    tracing::error!(%error, "Creating connection caused an error");
+                    metrics::CONNECTION_ERRORS.inc();
 
                     dist_sender
                         .send(EventInfo {
@@ -96,17 +226,19 @@ impl Manager {
                         })
                         .expect("Cannot send message");
 
-                    if let Some(handle) = &self.bridge_thread {
-                        handle.abort();
-                        self.bridge_thread = None;
+                    if self.bridge_thread.is_some() {
+                        self.stop_bridge();
+                        self.attempt_restart(&dist_sender, &bridge_receiver, &restart_pool)
+                            .await;
                     } else {
-                        panic!("Connection error when thread was already closed.");
-                        // continue
+                        tracing::warn!("Connection error arrived after the thread was already closed, ignoring");
                     }
                 }
                 EventType::Bridge(api_manager::models::BridgeEvents::TerminalRead { message }) => {
-                    println!("[Bridge] Received message: {}", message);
+                    let _enter = self.print_span.as_ref().map(|span| span.enter());
+                    tracing::debug!(%message, "Received message from bridge");
                     // todo: Group messages in "chunks", to make interface updates better to handle.
+                    metrics::COMMANDS_RECEIVED.inc();
 
                     dist_sender
                         .send(EventInfo {
@@ -120,6 +252,25 @@ impl Manager {
                     if self.bridge_thread.is_none() {
                         continue;
                     }
+
+                    let _enter = self.print_span.as_ref().map(|span| span.enter());
+
+                    if !command_rate_limiter.check() {
+                        tracing::warn!(%message, "Dropping command, rate limit exceeded");
+                        dist_sender
+                            .send(EventInfo {
+                                event_type: EventType::Websocket(WebsocketEvents::TerminalRead {
+                                    message: format!(
+                                        "Command throttled, sending too fast: {}",
+                                        message
+                                    ),
+                                }),
+                            })
+                            .expect("Cannot send message");
+                        continue;
+                    }
+
+                    metrics::COMMANDS_SENT.inc();
                     bridge_sender
                         .send(EventInfo {
                             event_type: EventType::Bridge(
@@ -138,6 +289,11 @@ impl Manager {
                         .expect("Cannot send message");
                 }
                 EventType::Bridge(api_manager::models::BridgeEvents::PrintEnd) => {
+                    if let Some(start) = self.print_start.take() {
+                        metrics::PRINT_JOB_DURATION.observe(start.elapsed().as_secs_f64());
+                    }
+                    self.print_span.take();
+
                     bridge_sender
                         .send(EventInfo {
                             event_type: EventType::Bridge(
@@ -150,6 +306,11 @@ impl Manager {
                     if self.bridge_thread.is_none() {
                         continue;
                     }
+                    self.print_start = Some(Instant::now());
+                    let span = tracing::info_span!("print_job", file_name = %info.file_name);
+                    let _enter = span.enter();
+                    tracing::info!("Print job started");
+                    self.print_span = Some(span.clone());
                     bridge_sender
                         .send(EventInfo {
                             event_type: EventType::Bridge(
@@ -166,12 +327,20 @@ impl Manager {
                         continue;
                     }
 
+                    metrics::BRIDGE_STATE.set(state.as_metric_value());
+
                     if state == BridgeState::DISCONNECTED || state == BridgeState::ERRORED {
                         let _ = bridge_sender.send(EventInfo {
                             event_type: EventType::KILL,
                         });
-                        self.bridge_thread.take();
+                        self.stop_bridge();
+                        self.attempt_restart(&dist_sender, &bridge_receiver, &restart_pool)
+                            .await;
                     } else {
+                        if state == BridgeState::CONNECTED {
+                            self.restart_attempts = 0;
+                        }
+
                         bridge_sender
                             .send(EventInfo {
                                 event_type: EventType::Bridge(
@@ -202,13 +371,97 @@ impl Manager {
                         })
                         .expect("Failed to send message to websocket");
                 }
+                EventType::Shutdown => {
+                    tracing::info!("Shutdown requested, draining pending events");
+                    self.stop_bridge();
+                    if let Some(handle) = self.api_thread.take() {
+                        handle.abort();
+                    }
+                    for event in dist_receiver.try_iter() {
+                        let _ = journal_sender.send(event);
+                    }
+                    drop(journal_sender);
+                    tracing::info!("Waiting for the journal to flush pending events");
+                    if let Err(err) = journal_handle.await {
+                        tracing::error!(%err, "Journal task panicked while flushing events");
+                    }
+                    break;
+                }
                 _ => (),
             }
         }
     }
 }
 
-async fn setup_db() {
+/// Initializes the tracing subscriber. When `B_enableTokioConsole` is set,
+/// the `console-subscriber` layer is installed instead of the usual fmt
+/// layer so operators can inspect live task/channel state with
+/// `tokio-console` — the two are mutually exclusive since console-subscriber
+/// takes over the global subscriber itself.
+async fn init_tracing(pool: &SqlitePool) {
+    let enable_tokio_console = sqlx::query_scalar::<_, String>(
+        "SELECT value FROM settings WHERE id = 'B_enableTokioConsole'",
+    )
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+    .map(|value| value == "true")
+    .unwrap_or(false);
+
+    if enable_tokio_console {
+        console_subscriber::init();
+    } else {
+        tracing_subscriber::fmt::init();
+    }
+}
+
+/// Waits for either Ctrl-C or SIGTERM, whichever arrives first.
+async fn wait_for_shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = sigterm.recv() => {}
+    }
+}
+
+/// Reads the `B_startOnBoot` setting.
+async fn start_on_boot(pool: &SqlitePool) -> bool {
+    sqlx::query_scalar::<_, String>("SELECT value FROM settings WHERE id = 'B_startOnBoot'")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+/// Resolves the bridge connection to start automatically on boot, if
+/// `B_startOnBoot` is enabled and a device has been configured.
+async fn start_on_boot_connection(pool: &SqlitePool) -> Option<(String, u32)> {
+    if !start_on_boot(pool).await {
+        return None;
+    }
+
+    let address: Option<String> =
+        sqlx::query_scalar("SELECT value FROM settings WHERE id = 'S_devicePath'")
+            .fetch_optional(pool)
+            .await
+            .ok()
+            .flatten();
+    let port: Option<String> =
+        sqlx::query_scalar("SELECT value FROM settings WHERE id = 'N_deviceBaud'")
+            .fetch_optional(pool)
+            .await
+            .ok()
+            .flatten();
+
+    let address = address.filter(|value| !value.is_empty())?;
+    let port = port.and_then(|value| value.parse().ok())?;
+    Some((address, port))
+}
+
+async fn setup_db() -> SqlitePool {
     let _ = OpenOptions::new()
         .write(true)
         .create_new(true)
@@ -231,13 +484,20 @@ async fn setup_db() {
             expire DATETIME,
             FOREIGN KEY(username) REFERENCES users(username) on update cascade on delete cascade
         );
-        
+
         CREATE TABLE IF NOT EXISTS settings (
             id varchar(255) primary key,
             value TEXT,
             type integer(3) not null
         );
 
+        CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            created_at DATETIME NOT NULL
+        );
+
         INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('S_devicePath', 0, null);
         INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('N_deviceBaud', 2, null);
         INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('B_startOnBoot', 1, false);
@@ -250,6 +510,10 @@ async fn setup_db() {
         INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('B_deviceHB', 1, false);
         INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('B_deviceHC', 1, false);
         INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('N_clientTerminalAmount', 2, 500);
+        INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('N_commandRateLimit', 2, 20);
+        INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('B_enableTokioConsole', 1, false);
+        INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('S_tlsCertPath', 0, null);
+        INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('S_tlsKeyPath', 0, null);
         INSERT OR IGNORE INTO SETTINGS (id, type, value) VALUES ('S_sentryDsn', 0, 'https://cd35379ff0fc45daa30a67bfe9aa8b36@0229745.ingest.sentry.io/5778789');
 
         DELETE FROM tokens where expire < DATE('now');
@@ -257,4 +521,10 @@ async fn setup_db() {
         )
         .await
         .expect("Error while creating tables.");
+
+    SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect("storage.db")
+        .await
+        .expect("Error while creating connection pool.")
 }
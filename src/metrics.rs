@@ -0,0 +1,67 @@
+use lazy_static::lazy_static;
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, Registry, TextEncoder};
+
+lazy_static! {
+    pub static ref REGISTRY: Registry = Registry::new();
+    pub static ref BRIDGE_STATE: IntGauge =
+        IntGauge::new("bridge_state", "Current state of the active bridge connection")
+            .expect("metric can be created");
+    pub static ref COMMANDS_SENT: IntCounter = IntCounter::new(
+        "commands_sent_total",
+        "Number of commands forwarded to the printer"
+    )
+    .expect("metric can be created");
+    pub static ref COMMANDS_RECEIVED: IntCounter = IntCounter::new(
+        "commands_received_total",
+        "Number of lines read back from the printer"
+    )
+    .expect("metric can be created");
+    pub static ref CONNECTION_ERRORS: IntCounter = IntCounter::new(
+        "connection_errors_total",
+        "Number of bridge connection errors"
+    )
+    .expect("metric can be created");
+    // Print jobs run minutes to hours, not milliseconds — the Prometheus
+    // default buckets top out at 10s, which would bucket every real
+    // observation into `+Inf`. Spread buckets from half a minute to 16 hours.
+    pub static ref PRINT_JOB_DURATION: Histogram = Histogram::with_opts(
+        HistogramOpts::new(
+            "print_job_duration_seconds",
+            "Duration of print jobs from PrintStart to PrintEnd"
+        )
+        .buckets(vec![
+            30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 14400.0, 28800.0, 57600.0,
+        ])
+    )
+    .expect("metric can be created");
+}
+
+/// Registers every metric with the global registry. Must be called once
+/// before `/metrics` is scraped.
+pub fn register() {
+    REGISTRY
+        .register(Box::new(BRIDGE_STATE.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(COMMANDS_SENT.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(COMMANDS_RECEIVED.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(CONNECTION_ERRORS.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(PRINT_JOB_DURATION.clone()))
+        .expect("collector can be registered");
+}
+
+/// Renders every registered metric in the Prometheus text exposition format.
+pub fn gather() -> String {
+    let metric_families = REGISTRY.gather();
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&metric_families, &mut buffer)
+        .expect("metrics can be encoded");
+    String::from_utf8(buffer).expect("metrics are valid utf8")
+}
@@ -0,0 +1,46 @@
+use governor::{Quota, RateLimiter};
+use sqlx::SqlitePool;
+use std::num::NonZeroU32;
+
+type Limiter = RateLimiter<
+    governor::state::direct::NotKeyed,
+    governor::state::InMemoryState,
+    governor::clock::DefaultClock,
+>;
+
+/// Guards the `TerminalSend` path so a misbehaving or scripted client can't
+/// flood the serial link and overrun the firmware's command buffer. Backed by
+/// a single global token bucket, since there is only ever one active bridge.
+pub struct CommandRateLimiter {
+    limiter: Limiter,
+}
+
+impl CommandRateLimiter {
+    pub fn new(commands_per_second: u32) -> Self {
+        let quota = Quota::per_second(NonZeroU32::new(commands_per_second.max(1)).unwrap());
+        Self {
+            limiter: RateLimiter::direct(quota),
+        }
+    }
+
+    /// Reads the `N_commandRateLimit` setting (commands per second), falling
+    /// back to a sensible default if it isn't configured yet.
+    pub async fn from_settings(pool: &SqlitePool) -> Self {
+        let commands_per_second = sqlx::query_scalar::<_, String>(
+            "SELECT value FROM settings WHERE id = 'N_commandRateLimit'",
+        )
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(20);
+
+        Self::new(commands_per_second)
+    }
+
+    /// Returns `true` if the command may be forwarded immediately.
+    pub fn check(&self) -> bool {
+        self.limiter.check().is_ok()
+    }
+}
@@ -0,0 +1,185 @@
+use futures_util::stream::{Stream, StreamExt};
+use sqlx::SqlitePool;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::{self, Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::TcpListenerStream;
+
+/// Either a plain TCP connection or one terminated by TLS, so the websocket
+/// server can accept both kinds of client through a single incoming stream.
+pub enum MaybeTlsStream {
+    Plain(TcpStream),
+    Tls(Box<tokio_rustls::server::TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Raised when TLS was configured (a cert/key path is set) but couldn't be
+/// loaded. Kept distinct from "not configured" so the caller never silently
+/// downgrades a broken TLS setup to plaintext.
+#[derive(Debug)]
+pub enum TlsSetupError {
+    Io(io::Error),
+    Rustls(rustls::Error),
+    Incomplete,
+}
+
+impl fmt::Display for TlsSetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsSetupError::Io(err) => write!(f, "failed to read TLS cert/key: {}", err),
+            TlsSetupError::Rustls(err) => write!(f, "failed to build TLS server config: {}", err),
+            TlsSetupError::Incomplete => {
+                write!(f, "only one of S_tlsCertPath/S_tlsKeyPath is set")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TlsSetupError {}
+
+impl From<io::Error> for TlsSetupError {
+    fn from(err: io::Error) -> Self {
+        TlsSetupError::Io(err)
+    }
+}
+
+impl From<rustls::Error> for TlsSetupError {
+    fn from(err: rustls::Error) -> Self {
+        TlsSetupError::Rustls(err)
+    }
+}
+
+/// Loads `S_tlsCertPath`/`S_tlsKeyPath` and builds a rustls `ServerConfig`.
+///
+/// Returns `Ok(None)` only when neither setting is configured, so the
+/// caller can fall back to plaintext. Once an operator has pointed at a
+/// cert/key, any failure to load it is a hard error — serving that
+/// connection in cleartext instead would silently leak the auth tokens and
+/// terminal traffic TLS was turned on to protect.
+pub async fn acceptor_from_settings(
+    pool: &SqlitePool,
+) -> Result<Option<TlsAcceptor>, TlsSetupError> {
+    let cert_path: Option<String> =
+        sqlx::query_scalar("SELECT value FROM settings WHERE id = 'S_tlsCertPath'")
+            .fetch_optional(pool)
+            .await
+            .ok()
+            .flatten();
+    let key_path: Option<String> =
+        sqlx::query_scalar("SELECT value FROM settings WHERE id = 'S_tlsKeyPath'")
+            .fetch_optional(pool)
+            .await
+            .ok()
+            .flatten();
+
+    let (cert_path, key_path) = match (cert_path, key_path) {
+        (None, None) => return Ok(None),
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        _ => return Err(TlsSetupError::Incomplete),
+    };
+
+    let certs = load_certs(&cert_path)?;
+    let key = load_key(&key_path)?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+fn load_certs(path: &str) -> io::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Parses whichever private key format the operator's PEM file is in —
+/// PKCS#8, PKCS#1/RSA, or SEC1/EC are all common depending on how the cert
+/// was issued.
+fn load_key(path: &str) -> io::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no supported private key found in {}", path),
+                ))
+            }
+        }
+    }
+}
+
+/// Accepts connections from `listener`, wrapping each one in TLS when
+/// `acceptor` is configured, otherwise passing the plain socket straight
+/// through.
+pub fn incoming(
+    listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+) -> impl Stream<Item = io::Result<MaybeTlsStream>> {
+    TcpListenerStream::new(listener).then(move |stream| {
+        let acceptor = acceptor.clone();
+        async move {
+            let stream = stream?;
+            match acceptor {
+                Some(acceptor) => acceptor
+                    .accept(stream)
+                    .await
+                    .map(|tls| MaybeTlsStream::Tls(Box::new(tls))),
+                None => Ok(MaybeTlsStream::Plain(stream)),
+            }
+        }
+    })
+}